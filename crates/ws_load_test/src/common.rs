@@ -0,0 +1,50 @@
+use std::convert::TryInto;
+use std::sync::{atomic::AtomicUsize, Arc, Mutex};
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+
+
+#[derive(Clone)]
+pub struct LoadTestState {
+    pub statistics: Arc<Statistics>,
+}
+
+
+pub struct Statistics {
+    pub connections: AtomicUsize,
+    pub requests: AtomicUsize,
+    pub responses_offer: AtomicUsize,
+    pub responses_answer: AtomicUsize,
+    pub responses_announce: AtomicUsize,
+    pub responses_scrape: AtomicUsize,
+    pub responses_error: AtomicUsize,
+    /// Round-trip latency of announce/scrape request-response pairs, in
+    /// microseconds. Offers/answers aren't request-paired and are excluded.
+    pub latencies: Mutex<Histogram<u64>>,
+}
+
+
+impl Statistics {
+    pub fn record_latency(&self, latency: Duration) {
+        let micros = latency.as_micros().try_into().unwrap_or(u64::MAX);
+
+        let _ = self.latencies.lock().unwrap().record(micros);
+    }
+}
+
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self {
+            connections: AtomicUsize::default(),
+            requests: AtomicUsize::default(),
+            responses_offer: AtomicUsize::default(),
+            responses_answer: AtomicUsize::default(),
+            responses_announce: AtomicUsize::default(),
+            responses_scrape: AtomicUsize::default(),
+            responses_error: AtomicUsize::default(),
+            latencies: Mutex::new(Histogram::new_with_bounds(1, 60_000_000, 3).unwrap()),
+        }
+    }
+}
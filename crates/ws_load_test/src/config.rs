@@ -0,0 +1,145 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+
+#[derive(Clone)]
+pub struct Config {
+    pub num_workers: usize,
+    pub num_connections_per_worker: usize,
+    pub connection_creation_interval_ms: u64,
+    pub network: NetworkConfig,
+    pub statistics: StatisticsConfig,
+    pub backoff: BackoffConfig,
+    pub ramp_up: RampUpConfig,
+}
+
+
+#[derive(Clone)]
+pub struct RampUpConfig {
+    /// Maximum number of new connections opened per second, per worker.
+    /// Set to `f64::INFINITY` to open connections as fast as
+    /// `num_connections_per_worker` allows.
+    pub max_connections_per_second: f64,
+    /// Duration over which the allowed number of concurrent connections is
+    /// linearly ramped up from 0 to `num_connections_per_worker`. Zero
+    /// disables ramp-up, allowing the full count immediately.
+    pub ramp_up_duration_ms: u64,
+}
+
+
+#[derive(Clone)]
+pub struct BackoffConfig {
+    /// Starting value of the delay. Every connection attempt first sleeps
+    /// a random duration of up to the current delay, which is doubled after
+    /// each failure.
+    pub min_backoff_ms: u64,
+    /// Upper bound the delay is capped at after repeated failures.
+    pub max_backoff_ms: u64,
+    /// How long a connection must stay up before the delay is reset back
+    /// to `min_backoff_ms`.
+    pub success_threshold_ms: u64,
+}
+
+
+#[derive(Clone)]
+pub struct NetworkConfig {
+    pub server_address: SocketAddr,
+    /// If true, connect with `wss://` (TLS). If false, connect with plain
+    /// `ws://` and ignore the TLS section entirely.
+    pub use_tls: bool,
+    pub tls: TlsConfig,
+}
+
+
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// Path to PEM file with root certificates to trust. If not set, the
+    /// root certificates bundled with the `webpki-roots` crate are used.
+    pub root_cert_path: Option<PathBuf>,
+    /// Path to PEM file with the client certificate chain to present for
+    /// mutual TLS. Requires `client_private_key_path` to also be set.
+    pub client_certificate_path: Option<PathBuf>,
+    /// Path to PEM file with the PKCS#8 private key matching
+    /// `client_certificate_path`.
+    pub client_private_key_path: Option<PathBuf>,
+    /// Server name sent via SNI and used for certificate validation.
+    pub server_name: String,
+    /// Accept any server certificate. Only meant for testing against
+    /// trackers with self-signed certificates.
+    pub danger_accept_invalid_certs: bool,
+}
+
+
+#[derive(Clone)]
+pub struct StatisticsConfig {
+    pub interval: u64,
+}
+
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            num_workers: 1,
+            num_connections_per_worker: 64,
+            connection_creation_interval_ms: 10,
+            network: NetworkConfig::default(),
+            statistics: StatisticsConfig::default(),
+            backoff: BackoffConfig::default(),
+            ramp_up: RampUpConfig::default(),
+        }
+    }
+}
+
+
+impl Default for RampUpConfig {
+    fn default() -> Self {
+        Self {
+            max_connections_per_second: f64::INFINITY,
+            ramp_up_duration_ms: 0,
+        }
+    }
+}
+
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            min_backoff_ms: 100,
+            max_backoff_ms: 30_000,
+            success_threshold_ms: 10_000,
+        }
+    }
+}
+
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            server_address: SocketAddr::from(([127, 0, 0, 1], 3000)),
+            use_tls: true,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            root_cert_path: None,
+            client_certificate_path: None,
+            client_private_key_path: None,
+            server_name: "example.com".into(),
+            danger_accept_invalid_certs: false,
+        }
+    }
+}
+
+
+impl Default for StatisticsConfig {
+    fn default() -> Self {
+        Self {
+            interval: 5,
+        }
+    }
+}
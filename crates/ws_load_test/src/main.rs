@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use glommio::{LocalExecutorBuilder, Placement};
+
+mod common;
+mod config;
+mod network;
+mod statistics;
+mod stream;
+mod tls;
+mod utils;
+
+use common::{LoadTestState, Statistics};
+use config::Config;
+
+fn main() -> anyhow::Result<()> {
+    let config = Config::default();
+    let tls_config = config
+        .network
+        .use_tls
+        .then(|| tls::create_client_config(&config.network.tls))
+        .transpose()?
+        .map(Arc::new);
+
+    let load_test_state = LoadTestState {
+        statistics: Arc::new(Statistics::default()),
+    };
+
+    let mut handles = Vec::new();
+
+    {
+        let statistics = load_test_state.statistics.clone();
+        let interval = Duration::from_secs(config.statistics.interval);
+
+        handles.push(
+            LocalExecutorBuilder::new(Placement::Unbound)
+                .spawn(move || statistics::run_statistics_printer(statistics, interval))?,
+        );
+    }
+
+    for _ in 0..config.num_workers {
+        let config = config.clone();
+        let tls_config = tls_config.clone();
+        let load_test_state = load_test_state.clone();
+
+        let handle = LocalExecutorBuilder::new(Placement::Unbound)
+            .spawn(move || network::run_socket_thread(config, tls_config, load_test_state))?;
+
+        handles.push(handle);
+    }
+
+    for handle in handles {
+        handle.join()??;
+    }
+
+    Ok(())
+}
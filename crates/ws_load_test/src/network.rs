@@ -3,7 +3,7 @@ use std::{
     convert::TryInto,
     rc::Rc,
     sync::{atomic::Ordering, Arc},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use aquatic_ws_protocol::{
@@ -11,20 +11,22 @@ use aquatic_ws_protocol::{
 };
 use async_tungstenite::{client_async, WebSocketStream};
 use futures::{SinkExt, StreamExt};
-use futures_rustls::{client::TlsStream, TlsConnector};
+use futures_rustls::TlsConnector;
 use glommio::net::TcpStream;
 use glommio::{prelude::*, timer::TimerActionRepeat};
 use rand::{prelude::SmallRng, Rng, SeedableRng};
 
-use crate::{common::LoadTestState, config::Config, utils::create_random_request};
+use crate::{common::LoadTestState, config::Config, stream::Stream, utils::create_random_request};
 
 pub async fn run_socket_thread(
     config: Config,
-    tls_config: Arc<rustls::ClientConfig>,
+    tls_config: Option<Arc<rustls::ClientConfig>>,
     load_test_state: LoadTestState,
 ) -> anyhow::Result<()> {
     let config = Rc::new(config);
     let num_active_connections = Rc::new(RefCell::new(0usize));
+    let backoff = Rc::new(RefCell::new(Backoff::new(&config.backoff)));
+    let opener = Rc::new(RefCell::new(ConnectionOpener::new(&config)));
     let connection_creation_interval =
         Duration::from_millis(config.connection_creation_interval_ms);
 
@@ -34,6 +36,8 @@ pub async fn run_socket_thread(
             tls_config.clone(),
             load_test_state.clone(),
             num_active_connections.clone(),
+            backoff.clone(),
+            opener.clone(),
             connection_creation_interval,
         )
     })
@@ -42,17 +46,333 @@ pub async fn run_socket_thread(
     .ok_or_else(|| anyhow::anyhow!("connection opener timer cancelled"))
 }
 
+/// Rate-controlled connection ramp-up: limits how many new connections are
+/// opened per tick to a configured connections-per-second rate, and
+/// linearly increases the allowed number of concurrent connections from 0
+/// up to `num_connections_per_worker` over a ramp window.
+struct ConnectionOpener {
+    start: Instant,
+    last_tick: Instant,
+    tokens: f64,
+    max_connections_per_second: f64,
+    ramp_up_duration: Duration,
+    target_connections: usize,
+    /// Connections dispatched but not yet connected or failed. Reserved as
+    /// soon as an attempt is dispatched and released once it resolves, so
+    /// a slow handshake can't make the same room look free on every tick.
+    in_flight: usize,
+}
+
+impl ConnectionOpener {
+    fn new(config: &Config) -> Self {
+        let now = Instant::now();
+
+        Self {
+            start: now,
+            last_tick: now,
+            tokens: 0.0,
+            max_connections_per_second: config.ramp_up.max_connections_per_second,
+            ramp_up_duration: Duration::from_millis(config.ramp_up.ramp_up_duration_ms),
+            target_connections: config.num_connections_per_worker,
+            in_flight: 0,
+        }
+    }
+
+    /// Releases a slot reserved by a dispatched connection attempt once it
+    /// has connected (and is now counted by `num_active_connections`
+    /// instead) or failed.
+    fn release(&mut self) {
+        self.in_flight -= 1;
+    }
+
+    fn allowed_concurrency(&self) -> usize {
+        self.allowed_concurrency_at(self.start.elapsed())
+    }
+
+    /// Pure version of `allowed_concurrency` taking time-since-start
+    /// explicitly, so the ramp-up fraction can be tested deterministically.
+    fn allowed_concurrency_at(&self, elapsed_since_start: Duration) -> usize {
+        if self.ramp_up_duration.is_zero() || elapsed_since_start >= self.ramp_up_duration {
+            return self.target_connections;
+        }
+
+        let fraction =
+            elapsed_since_start.as_secs_f64() / self.ramp_up_duration.as_secs_f64();
+
+        ((self.target_connections as f64) * fraction).floor() as usize
+    }
+
+    /// Returns how many new connections may be opened right now, given
+    /// `active` currently-open connections. The returned count is also
+    /// reserved as in-flight until `release` is called for each one.
+    fn connections_to_open(&mut self, active: usize) -> usize {
+        let now = Instant::now();
+        let elapsed_since_last_tick = now.duration_since(self.last_tick);
+        let elapsed_since_start = now.duration_since(self.start);
+        self.last_tick = now;
+
+        self.tick(active, elapsed_since_last_tick, elapsed_since_start)
+    }
+
+    /// Pure version of `connections_to_open` taking the tick/start elapsed
+    /// times explicitly instead of reading the clock, so the token-bucket
+    /// and ramp-up math can be tested deterministically.
+    fn tick(
+        &mut self,
+        active: usize,
+        elapsed_since_last_tick: Duration,
+        elapsed_since_start: Duration,
+    ) -> usize {
+        if self.max_connections_per_second.is_finite() {
+            self.tokens = (self.tokens
+                + self.max_connections_per_second * elapsed_since_last_tick.as_secs_f64())
+            .min(self.max_connections_per_second.max(1.0));
+        } else {
+            self.tokens = f64::INFINITY;
+        }
+
+        let room = self
+            .allowed_concurrency_at(elapsed_since_start)
+            .saturating_sub(active + self.in_flight) as f64;
+        let allowed = self.tokens.min(room).max(0.0).floor();
+
+        if allowed.is_finite() {
+            self.tokens -= allowed;
+        }
+
+        let allowed = allowed as usize;
+        self.in_flight += allowed;
+
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod connection_opener_tests {
+    use super::*;
+
+    fn opener(max_connections_per_second: f64, ramp_up_duration_ms: u64) -> ConnectionOpener {
+        let now = Instant::now();
+
+        ConnectionOpener {
+            start: now,
+            last_tick: now,
+            tokens: 0.0,
+            max_connections_per_second,
+            ramp_up_duration: Duration::from_millis(ramp_up_duration_ms),
+            target_connections: 100,
+            in_flight: 0,
+        }
+    }
+
+    #[test]
+    fn unlimited_rate_opens_up_to_full_concurrency_immediately() {
+        let mut opener = opener(f64::INFINITY, 0);
+
+        assert_eq!(opener.tick(0, Duration::ZERO, Duration::ZERO), 100);
+    }
+
+    #[test]
+    fn rate_limits_to_at_most_one_tick_worth_of_tokens() {
+        let mut opener = opener(10.0, 0);
+
+        // Half a second at 10/s accrues 5 tokens.
+        let to_open = opener.tick(0, Duration::from_millis(500), Duration::ZERO);
+        assert_eq!(to_open, 5);
+    }
+
+    #[test]
+    fn never_opens_more_than_the_room_left_under_the_cap() {
+        let mut opener = opener(1_000.0, 0);
+
+        // Plenty of tokens accrue, but only 3 connections short of the cap.
+        let to_open = opener.tick(97, Duration::from_secs(1), Duration::ZERO);
+        assert_eq!(to_open, 3);
+    }
+
+    #[test]
+    fn does_not_redispatch_while_attempts_are_still_in_flight() {
+        let mut opener = opener(1_000.0, 0);
+
+        // First tick dispatches connections up to the cap and reserves them
+        // as in-flight, even though `active` (the connected count) hasn't
+        // moved yet because none of them have finished connecting.
+        let to_open = opener.tick(0, Duration::from_secs(1), Duration::ZERO);
+        assert_eq!(to_open, 100);
+
+        // A slow-handshake tick later, `active` is still 0, but there's no
+        // room left because everything dispatched is still in flight.
+        let to_open = opener.tick(0, Duration::from_secs(1), Duration::ZERO);
+        assert_eq!(to_open, 0);
+
+        // Once attempts resolve (connect or fail) and release their slot,
+        // room opens back up.
+        for _ in 0..100 {
+            opener.release();
+        }
+
+        let to_open = opener.tick(0, Duration::from_secs(1), Duration::ZERO);
+        assert_eq!(to_open, 100);
+    }
+
+    #[test]
+    fn ramp_up_scales_allowed_concurrency_linearly() {
+        let opener = opener(f64::INFINITY, 10_000);
+
+        assert_eq!(opener.allowed_concurrency_at(Duration::ZERO), 0);
+        assert_eq!(opener.allowed_concurrency_at(Duration::from_secs(5)), 50);
+        assert_eq!(opener.allowed_concurrency_at(Duration::from_secs(10)), 100);
+        assert_eq!(opener.allowed_concurrency_at(Duration::from_secs(20)), 100);
+    }
+
+    #[test]
+    fn ramp_up_caps_connections_opened_before_window_elapses() {
+        let mut opener = opener(1_000.0, 10_000);
+
+        // At the 10% mark only 10 connections are allowed in total.
+        let to_open = opener.tick(0, Duration::from_secs(1), Duration::from_secs(1));
+        assert_eq!(to_open, 10);
+    }
+}
+
+/// Per-worker capped exponential backoff with full jitter, shared by all
+/// connection attempts made on this worker so failures don't produce a
+/// synchronized retry storm.
+struct Backoff {
+    min_backoff: Duration,
+    max_backoff: Duration,
+    success_threshold: Duration,
+    current_delay: Duration,
+}
+
+impl Backoff {
+    fn new(config: &crate::config::BackoffConfig) -> Self {
+        let min_backoff = Duration::from_millis(config.min_backoff_ms);
+
+        Self {
+            min_backoff,
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            success_threshold: Duration::from_millis(config.success_threshold_ms),
+            current_delay: min_backoff,
+        }
+    }
+
+    fn jittered_delay(&self, rng: &mut impl Rng) -> Duration {
+        rng.gen_range(Duration::ZERO..=self.current_delay)
+    }
+
+    fn record_failure(&mut self) {
+        self.current_delay = (self.current_delay * 2).min(self.max_backoff);
+    }
+
+    fn record_outcome(&mut self, time_connected: Duration) {
+        if time_connected >= self.success_threshold {
+            self.current_delay = self.min_backoff;
+        } else {
+            self.record_failure();
+        }
+    }
+}
+
+#[cfg(test)]
+mod backoff_tests {
+    use super::*;
+    use crate::config::BackoffConfig;
+
+    fn backoff() -> Backoff {
+        Backoff::new(&BackoffConfig {
+            min_backoff_ms: 100,
+            max_backoff_ms: 1_000,
+            success_threshold_ms: 5_000,
+        })
+    }
+
+    #[test]
+    fn doubles_on_repeated_failures() {
+        let mut backoff = backoff();
+
+        assert_eq!(backoff.current_delay, Duration::from_millis(100));
+
+        backoff.record_failure();
+        assert_eq!(backoff.current_delay, Duration::from_millis(200));
+
+        backoff.record_failure();
+        assert_eq!(backoff.current_delay, Duration::from_millis(400));
+    }
+
+    #[test]
+    fn caps_at_max_backoff() {
+        let mut backoff = backoff();
+
+        for _ in 0..10 {
+            backoff.record_failure();
+        }
+
+        assert_eq!(backoff.current_delay, Duration::from_millis(1_000));
+    }
+
+    #[test]
+    fn resets_after_staying_up_past_success_threshold() {
+        let mut backoff = backoff();
+
+        backoff.record_failure();
+        backoff.record_failure();
+        assert_eq!(backoff.current_delay, Duration::from_millis(400));
+
+        backoff.record_outcome(Duration::from_millis(5_001));
+        assert_eq!(backoff.current_delay, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn treats_short_connection_as_a_failure() {
+        let mut backoff = backoff();
+
+        backoff.record_outcome(Duration::from_millis(1));
+        assert_eq!(backoff.current_delay, Duration::from_millis(200));
+    }
+
+    #[test]
+    fn jittered_delay_never_exceeds_current_delay() {
+        let backoff = backoff();
+        let mut rng = SmallRng::seed_from_u64(42);
+
+        for _ in 0..100 {
+            let delay = backoff.jittered_delay(&mut rng);
+            assert!(delay <= backoff.current_delay);
+        }
+    }
+}
+
 async fn periodically_open_connections(
     config: Rc<Config>,
-    tls_config: Arc<rustls::ClientConfig>,
+    tls_config: Option<Arc<rustls::ClientConfig>>,
     load_test_state: LoadTestState,
     num_active_connections: Rc<RefCell<usize>>,
+    backoff: Rc<RefCell<Backoff>>,
+    opener: Rc<RefCell<ConnectionOpener>>,
     connection_creation_interval: Duration,
 ) -> Option<Duration> {
-    if *num_active_connections.borrow() < config.num_connections_per_worker {
+    let active = *num_active_connections.borrow();
+    let to_open = opener.borrow_mut().connections_to_open(active);
+
+    for _ in 0..to_open {
+        let config = config.clone();
+        let tls_config = tls_config.clone();
+        let load_test_state = load_test_state.clone();
+        let num_active_connections = num_active_connections.clone();
+        let backoff = backoff.clone();
+        let opener = opener.clone();
+
         spawn_local(async move {
-            if let Err(err) =
-                Connection::run(config, tls_config, load_test_state, num_active_connections).await
+            if let Err(err) = Connection::run(
+                config,
+                tls_config,
+                load_test_state,
+                num_active_connections,
+                backoff,
+                opener,
+            )
+            .await
             {
                 ::log::info!("connection creation error: {:#}", err);
             }
@@ -63,37 +383,175 @@ async fn periodically_open_connections(
     Some(connection_creation_interval)
 }
 
+/// Which kind of request is currently awaiting a response, so that
+/// `read_message` knows which round trip to stop timing. Offers/answers
+/// aren't strictly request-paired and so aren't tracked here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PendingRequest {
+    Announce,
+    Scrape,
+}
+
+/// Tracks the single outstanding announce/scrape request a connection is
+/// waiting on, so its round-trip latency can be measured. Plain state
+/// machine with no socket I/O, so it can be unit tested directly.
+#[derive(Default)]
+struct PendingRequestTracker {
+    pending: Option<(PendingRequest, Instant)>,
+}
+
+impl PendingRequestTracker {
+    /// Records `kind` as sent at `now`, unless a request is already
+    /// outstanding - an unresolved request must not be clobbered by the
+    /// next one sent, or its eventual response would be timed against the
+    /// wrong send timestamp.
+    fn mark_sent(&mut self, kind: PendingRequest, now: Instant) {
+        if self.pending.is_none() {
+            self.pending = Some((kind, now));
+        }
+    }
+
+    /// If `kind` matches the outstanding request, clears it and returns the
+    /// elapsed time since it was sent. Returns `None` without touching the
+    /// pending request on a mismatch, e.g. when a scrape response is read
+    /// while an announce request is outstanding.
+    fn resolve(&mut self, kind: PendingRequest, now: Instant) -> Option<Duration> {
+        match self.pending {
+            Some((pending_kind, sent_at)) if pending_kind == kind => {
+                self.pending = None;
+
+                Some(now.duration_since(sent_at))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod pending_request_tracker_tests {
+    use super::*;
+
+    #[test]
+    fn resolve_clears_pending_on_a_matching_response() {
+        let mut tracker = PendingRequestTracker::default();
+        let sent_at = Instant::now();
+
+        tracker.mark_sent(PendingRequest::Announce, sent_at);
+
+        let latency = tracker.resolve(
+            PendingRequest::Announce,
+            sent_at + Duration::from_millis(50),
+        );
+
+        assert_eq!(latency, Some(Duration::from_millis(50)));
+        assert!(tracker.pending.is_none());
+    }
+
+    #[test]
+    fn resolve_is_a_no_op_on_a_mismatched_kind() {
+        let mut tracker = PendingRequestTracker::default();
+        let sent_at = Instant::now();
+
+        tracker.mark_sent(PendingRequest::Announce, sent_at);
+
+        let latency = tracker.resolve(
+            PendingRequest::Scrape,
+            sent_at + Duration::from_millis(50),
+        );
+
+        assert_eq!(latency, None);
+        assert_eq!(tracker.pending, Some((PendingRequest::Announce, sent_at)));
+    }
+
+    #[test]
+    fn mark_sent_does_not_clobber_an_outstanding_request() {
+        let mut tracker = PendingRequestTracker::default();
+        let first_sent_at = Instant::now();
+        let second_sent_at = first_sent_at + Duration::from_millis(10);
+
+        // First request sent, still awaiting its response (e.g. an
+        // unrelated offer was read in between).
+        tracker.mark_sent(PendingRequest::Announce, first_sent_at);
+        tracker.mark_sent(PendingRequest::Announce, second_sent_at);
+
+        assert_eq!(
+            tracker.pending,
+            Some((PendingRequest::Announce, first_sent_at))
+        );
+
+        // The real response to the first request now arrives and is timed
+        // against the first, not the second, send timestamp.
+        let latency = tracker.resolve(
+            PendingRequest::Announce,
+            first_sent_at + Duration::from_millis(100),
+        );
+
+        assert_eq!(latency, Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn mark_sent_starts_tracking_again_once_cleared() {
+        let mut tracker = PendingRequestTracker::default();
+        let first_sent_at = Instant::now();
+
+        tracker.mark_sent(PendingRequest::Scrape, first_sent_at);
+        tracker.resolve(PendingRequest::Scrape, first_sent_at);
+
+        let second_sent_at = first_sent_at + Duration::from_millis(10);
+        tracker.mark_sent(PendingRequest::Scrape, second_sent_at);
+
+        assert_eq!(
+            tracker.pending,
+            Some((PendingRequest::Scrape, second_sent_at))
+        );
+    }
+}
+
 struct Connection {
     config: Rc<Config>,
     load_test_state: LoadTestState,
     rng: SmallRng,
     peer_id: PeerId,
     can_send_answer: Option<(InfoHash, PeerId, OfferId)>,
-    stream: WebSocketStream<TlsStream<TcpStream>>,
+    pending_request: PendingRequestTracker,
+    stream: WebSocketStream<Stream>,
 }
 
 impl Connection {
     async fn run(
         config: Rc<Config>,
-        tls_config: Arc<rustls::ClientConfig>,
+        tls_config: Option<Arc<rustls::ClientConfig>>,
         load_test_state: LoadTestState,
         num_active_connections: Rc<RefCell<usize>>,
+        backoff: Rc<RefCell<Backoff>>,
+        opener: Rc<RefCell<ConnectionOpener>>,
     ) -> anyhow::Result<()> {
         let mut rng = SmallRng::from_entropy();
-        let peer_id = PeerId(rng.gen());
-        let stream = TcpStream::connect(config.server_address)
-            .await
-            .map_err(|err| anyhow::anyhow!("connect: {:?}", err))?;
-        let stream = TlsConnector::from(tls_config)
-            .connect("example.com".try_into().unwrap(), stream)
-            .await?;
-        let request = format!(
-            "ws://{}:{}",
-            config.server_address.ip(),
-            config.server_address.port()
-        );
-        let (stream, _) = client_async(request, stream).await?;
 
+        let delay = backoff.borrow().jittered_delay(&mut rng);
+        glommio::timer::sleep(delay).await;
+
+        let connected_at = Instant::now();
+
+        let result = Self::connect(&config, tls_config).await;
+
+        // The in-flight slot reserved by the opener only covers the
+        // connecting phase; release it as soon as that phase resolves
+        // (successfully or not) rather than waiting for the connection to
+        // close, since a successful connection is tracked via
+        // `num_active_connections` from here on.
+        opener.borrow_mut().release();
+
+        let stream = match result {
+            Ok(stream) => stream,
+            Err(err) => {
+                backoff.borrow_mut().record_failure();
+
+                return Err(err);
+            }
+        };
+
+        let peer_id = PeerId(rng.gen());
         let statistics = load_test_state.statistics.clone();
 
         let mut connection = Connection {
@@ -103,6 +561,7 @@ impl Connection {
             stream,
             peer_id,
             can_send_answer: None,
+            pending_request: PendingRequestTracker::default(),
         };
 
         *num_active_connections.borrow_mut() += 1;
@@ -115,9 +574,49 @@ impl Connection {
         *num_active_connections.borrow_mut() -= 1;
         statistics.connections.fetch_sub(1, Ordering::Relaxed);
 
+        backoff
+            .borrow_mut()
+            .record_outcome(connected_at.elapsed());
+
         Ok(())
     }
 
+    async fn connect(
+        config: &Config,
+        tls_config: Option<Arc<rustls::ClientConfig>>,
+    ) -> anyhow::Result<WebSocketStream<Stream>> {
+        let tcp_stream = TcpStream::connect(config.network.server_address)
+            .await
+            .map_err(|err| anyhow::anyhow!("connect: {:?}", err))?;
+
+        let stream = if config.network.use_tls {
+            let tls_config = tls_config
+                .ok_or_else(|| anyhow::anyhow!("use_tls is set but no TLS config was built"))?;
+
+            let server_name: rustls::ServerName =
+                config.network.tls.server_name.as_str().try_into().map_err(|_| {
+                    anyhow::anyhow!("invalid TLS server name: {}", config.network.tls.server_name)
+                })?;
+
+            let tls_stream = TlsConnector::from(tls_config)
+                .connect(server_name, tcp_stream)
+                .await?;
+
+            Stream::Tls(tls_stream)
+        } else {
+            Stream::Plain(tcp_stream)
+        };
+
+        let request = format!(
+            "ws://{}:{}",
+            config.network.server_address.ip(),
+            config.network.server_address.port()
+        );
+        let (stream, _) = client_async(request, stream).await?;
+
+        Ok(stream)
+    }
+
     async fn run_connection_loop(&mut self) -> anyhow::Result<()> {
         loop {
             self.send_message().await?;
@@ -156,6 +655,13 @@ impl Connection {
             request
         };
 
+        let kind = match &request {
+            InMessage::AnnounceRequest(_) => PendingRequest::Announce,
+            InMessage::ScrapeRequest(_) => PendingRequest::Scrape,
+        };
+
+        self.pending_request.mark_sent(kind, Instant::now());
+
         self.stream.send(request.to_ws_message()).await?;
 
         self.load_test_state
@@ -206,12 +712,16 @@ impl Connection {
                     .statistics
                     .responses_announce
                     .fetch_add(1, Ordering::Relaxed);
+
+                self.record_latency_if_pending(PendingRequest::Announce);
             }
             Ok(OutMessage::ScrapeResponse(_)) => {
                 self.load_test_state
                     .statistics
                     .responses_scrape
                     .fetch_add(1, Ordering::Relaxed);
+
+                self.record_latency_if_pending(PendingRequest::Scrape);
             }
             Ok(OutMessage::ErrorResponse(response)) => {
                 self.load_test_state
@@ -228,4 +738,14 @@ impl Connection {
 
         Ok(())
     }
+
+    /// Records round-trip latency if the outstanding request (the oldest
+    /// unresolved one) is of kind `kind`, then clears the pending marker.
+    /// On a mismatch the pending request is left untouched and is timed
+    /// against the next response of its own kind.
+    fn record_latency_if_pending(&mut self, kind: PendingRequest) {
+        if let Some(latency) = self.pending_request.resolve(kind, Instant::now()) {
+            self.load_test_state.statistics.record_latency(latency);
+        }
+    }
 }
@@ -0,0 +1,46 @@
+use std::sync::{atomic::Ordering, Arc};
+use std::time::{Duration, Instant};
+
+use crate::common::Statistics;
+
+/// Periodically prints throughput and round-trip latency, run once for the
+/// whole load test (not per worker).
+pub async fn run_statistics_printer(statistics: Arc<Statistics>, interval: Duration) -> anyhow::Result<()> {
+    let mut last_requests = 0;
+    let mut last_responses = 0;
+    let mut last_tick = Instant::now();
+
+    loop {
+        glommio::timer::sleep(interval).await;
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(last_tick).as_secs_f64();
+        last_tick = now;
+
+        let requests = statistics.requests.load(Ordering::Relaxed);
+        let responses = statistics.responses_announce.load(Ordering::Relaxed)
+            + statistics.responses_scrape.load(Ordering::Relaxed)
+            + statistics.responses_offer.load(Ordering::Relaxed)
+            + statistics.responses_answer.load(Ordering::Relaxed)
+            + statistics.responses_error.load(Ordering::Relaxed);
+
+        let requests_per_second = (requests - last_requests) as f64 / elapsed;
+        let responses_per_second = (responses - last_responses) as f64 / elapsed;
+
+        last_requests = requests;
+        last_responses = responses;
+
+        let histogram = statistics.latencies.lock().unwrap();
+
+        ::log::info!(
+            "connections: {}, requests/s: {:.0}, responses/s: {:.0}, latency p50/p90/p99/max (ms): {:.1}/{:.1}/{:.1}/{:.1}",
+            statistics.connections.load(Ordering::Relaxed),
+            requests_per_second,
+            responses_per_second,
+            histogram.value_at_quantile(0.50) as f64 / 1000.0,
+            histogram.value_at_quantile(0.90) as f64 / 1000.0,
+            histogram.value_at_quantile(0.99) as f64 / 1000.0,
+            histogram.max() as f64 / 1000.0,
+        );
+    }
+}
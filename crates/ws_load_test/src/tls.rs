@@ -0,0 +1,98 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, Error, PrivateKey, RootCertStore, ServerName};
+
+use crate::config::TlsConfig;
+
+/// Build the `rustls::ClientConfig` shared by the connections of all
+/// workers, according to the TLS section of `Config`.
+pub fn create_client_config(config: &TlsConfig) -> anyhow::Result<ClientConfig> {
+    let builder = ClientConfig::builder().with_safe_defaults();
+
+    // Both branches yield a `ConfigBuilder<ClientConfig, WantsClientCert>`,
+    // so client cert/key handling below applies regardless of whether the
+    // insecure verifier or the real root store is used.
+    let builder = if config.danger_accept_invalid_certs {
+        builder.with_custom_certificate_verifier(Arc::new(AcceptAnyServerCert))
+    } else {
+        let mut root_store = RootCertStore::empty();
+
+        if let Some(path) = &config.root_cert_path {
+            for cert in load_certs(path)? {
+                root_store.add(&cert)?;
+            }
+        } else {
+            root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+                rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    ta.subject,
+                    ta.spki,
+                    ta.name_constraints,
+                )
+            }));
+        }
+
+        builder.with_root_certificates(root_store)
+    };
+
+    let client_config = match (&config.client_certificate_path, &config.client_private_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = load_certs(cert_path)?;
+            let key = load_private_key(key_path)?;
+
+            builder.with_client_auth_cert(certs, key)?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "client_certificate_path and client_private_key_path must be set together"
+            ))
+        }
+    };
+
+    Ok(client_config)
+}
+
+fn load_certs(path: &std::path::Path) -> anyhow::Result<Vec<Certificate>> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    Ok(rustls_pemfile::certs(&mut reader)?
+        .into_iter()
+        .map(Certificate)
+        .collect())
+}
+
+fn load_private_key(path: &std::path::Path) -> anyhow::Result<PrivateKey> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    let mut keys = rustls_pemfile::pkcs8_private_keys(&mut reader)?;
+
+    let key = keys
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("no PKCS#8 private key found in {:?}", path))?;
+
+    Ok(PrivateKey(key))
+}
+
+/// Accepts any server certificate. Only meant to be opted into explicitly
+/// for testing against trackers presenting self-signed certificates.
+struct AcceptAnyServerCert;
+
+impl ServerCertVerifier for AcceptAnyServerCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
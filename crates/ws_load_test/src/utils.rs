@@ -0,0 +1,34 @@
+use aquatic_ws_protocol::{AnnounceEvent, AnnounceRequest, InMessage, InfoHash, PeerId};
+use rand::Rng;
+
+use crate::{common::LoadTestState, config::Config};
+
+/// Create a random announce request. Offer/answer handling is grafted on by
+/// the caller when a pending answer is owed to a peer.
+pub fn create_random_request(
+    _config: &Config,
+    load_test_state: &LoadTestState,
+    rng: &mut impl Rng,
+    peer_id: PeerId,
+    with_event: bool,
+) -> InMessage {
+    let info_hash = InfoHash(rng.gen());
+
+    let _ = load_test_state;
+
+    InMessage::AnnounceRequest(AnnounceRequest {
+        info_hash,
+        peer_id,
+        bytes_left: rng.gen_range(0..1_000_000),
+        event: if with_event {
+            Some(AnnounceEvent::Started)
+        } else {
+            None
+        },
+        offers: None,
+        answer: None,
+        answer_to_peer_id: None,
+        answer_offer_id: None,
+        numwant: Some(rng.gen_range(0..10)),
+    })
+}